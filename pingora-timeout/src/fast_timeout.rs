@@ -0,0 +1,198 @@
+// Copyright 2024 Cloudflare, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The fast, tick-coalesced timeout and sleep built on top of [`crate::timer`].
+
+use crate::timer::{timer_manager, Instant};
+use crate::{Elapsed, Timeout, ToTimeout};
+use futures::future::BoxFuture;
+use futures::Stream;
+use pin_project_lite::pin_project;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{self, Poll};
+use tokio::time::Duration;
+
+/// The timer generated by [fast_timeout()].
+///
+/// Users don't need to interact with this object.
+pub struct FastTimeout(Duration);
+
+impl ToTimeout for FastTimeout {
+    fn timeout(&self) -> BoxFuture<'static, ()> {
+        timer_manager().timeout(self.0)
+    }
+
+    fn create(d: Duration) -> Self {
+        FastTimeout(d)
+    }
+}
+
+/// Same as [tokio::time::timeout] but more efficient, see the [crate] docs.
+pub fn fast_timeout<T>(duration: Duration, future: T) -> Timeout<T, FastTimeout>
+where
+    T: Future,
+{
+    Timeout::<T, FastTimeout>::new_with_delay(future, duration)
+}
+
+/// Same as [tokio::time::sleep] but more efficient, see the [crate] docs.
+pub fn fast_sleep(duration: Duration) -> BoxFuture<'static, ()> {
+    timer_manager().timeout(duration)
+}
+
+/// The timer generated by [fast_timeout_at()].
+///
+/// Users don't need to interact with this object.
+pub struct FastTimeoutAt(Instant);
+
+impl ToTimeout for FastTimeoutAt {
+    fn timeout(&self) -> BoxFuture<'static, ()> {
+        timer_manager().timeout_at(self.0)
+    }
+
+    fn create(d: Duration) -> Self {
+        FastTimeoutAt(Instant::now() + d)
+    }
+
+    fn create_at(deadline: Instant) -> Self {
+        FastTimeoutAt(deadline)
+    }
+}
+
+/// Same as [tokio::time::timeout_at] but more efficient, see the [crate] docs.
+///
+/// Unlike [fast_timeout()], the deadline is an absolute [Instant] rather than a [Duration] from
+/// now. Many concurrent operations that share one deadline (instead of each computing their own
+/// duration) collapse onto exactly one shared timer entry.
+pub fn fast_timeout_at<T>(deadline: Instant, future: T) -> Timeout<T, FastTimeoutAt>
+where
+    T: Future,
+{
+    Timeout::<T, FastTimeoutAt>::new_with_deadline(future, deadline)
+}
+
+pin_project! {
+    /// A [Stream] adapter that gives every yielded item up to `duration` to arrive.
+    ///
+    /// See [fast_timeout_stream()].
+    #[must_use = "streams do nothing unless polled"]
+    pub struct TimeoutStream<S> {
+        #[pin]
+        stream: S,
+        #[pin]
+        delay: Option<BoxFuture<'static, ()>>,
+        duration: Duration,
+    }
+}
+
+impl<S> TimeoutStream<S> {
+    fn new(stream: S, duration: Duration) -> Self {
+        TimeoutStream {
+            stream,
+            delay: None,
+            duration,
+        }
+    }
+}
+
+impl<S> Stream for TimeoutStream<S>
+where
+    S: Stream,
+{
+    type Item = Result<S::Item, Elapsed>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut me = self.project();
+
+        if let Poll::Ready(item) = me.stream.as_mut().poll_next(cx) {
+            // An item arrived (or the stream ended): drop the pending delay so the next item
+            // starts with a fresh window instead of inheriting this one's remaining time.
+            me.delay.set(None);
+            return Poll::Ready(item.map(Ok));
+        }
+
+        let delay = me
+            .delay
+            .get_or_insert_with(|| timer_manager().timeout(*me.duration));
+
+        match delay.as_mut().poll(cx) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(()) => {
+                // Reset the delay so the next poll arms a fresh window rather than firing
+                // `Elapsed` again immediately; the stream itself isn't ended by a timeout.
+                me.delay.set(None);
+                Poll::Ready(Some(Err(Elapsed {})))
+            }
+        }
+    }
+}
+
+/// Wrap `stream` so each yielded item has up to `duration` to arrive, reusing the same
+/// tick-rounded, shared-timer machinery as [fast_timeout()].
+///
+/// A timeout doesn't end the stream: polling again after a `Some(Err(Elapsed))` arms a fresh
+/// `duration` window for the next item.
+pub fn fast_timeout_stream<S>(duration: Duration, stream: S) -> TimeoutStream<S>
+where
+    S: Stream,
+{
+    TimeoutStream::new(stream, duration)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use tokio::time::sleep;
+
+    #[tokio::test]
+    async fn test_timeout_stream_yields_items() {
+        let s = futures::stream::iter(vec![1, 2, 3]);
+        let mut s = fast_timeout_stream(Duration::from_secs(1), s);
+        assert_eq!(s.next().await.unwrap().unwrap(), 1);
+        assert_eq!(s.next().await.unwrap().unwrap(), 2);
+        assert_eq!(s.next().await.unwrap().unwrap(), 3);
+        assert!(s.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_timeout_stream_elapsed_then_recovers() {
+        // The first item is slow to arrive; later items are instant. It's slower than a single
+        // window, so it may elapse more than once before it's ready.
+        let s = futures::stream::unfold(0, |i| async move {
+            if i == 0 {
+                sleep(Duration::from_millis(120)).await;
+            }
+            (i < 2).then_some((i, i + 1))
+        });
+        let s = fast_timeout_stream(Duration::from_millis(50), s);
+        tokio::pin!(s);
+
+        // the timeout doesn't end the stream: each elapsed window just gives the slow item a
+        // fresh one, until it's finally ready
+        let mut elapsed_count = 0;
+        let first = loop {
+            match s.next().await.unwrap() {
+                Ok(item) => break item,
+                Err(_) => elapsed_count += 1,
+            }
+        };
+        assert!(elapsed_count >= 1);
+        assert_eq!(first, 0);
+
+        assert_eq!(s.next().await.unwrap().unwrap(), 1);
+        assert!(s.next().await.is_none());
+    }
+}
@@ -38,12 +38,15 @@ pub mod timer;
 
 pub use fast_timeout::fast_sleep as sleep;
 pub use fast_timeout::fast_timeout as timeout;
+pub use fast_timeout::fast_timeout_at as timeout_at;
+pub use fast_timeout::{fast_timeout_stream, TimeoutStream};
 
 use futures::future::BoxFuture;
 use pin_project_lite::pin_project;
 use std::future::Future;
 use std::pin::Pin;
 use std::task::{self, Poll};
+use std::time::Instant;
 use tokio::time::{sleep as tokio_sleep, Duration};
 
 /// The interface to start a timeout
@@ -52,6 +55,19 @@ use tokio::time::{sleep as tokio_sleep, Duration};
 pub trait ToTimeout {
     fn timeout(&self) -> BoxFuture<'static, ()>;
     fn create(d: Duration) -> Self;
+
+    /// Create the timeout from an absolute deadline instead of a relative duration.
+    ///
+    /// The default implementation just derives a duration relative to now, which is fine for
+    /// timers that are keyed by duration. Implementations backed by a deadline-keyed shared
+    /// timer (like the fast timeout) should override this to avoid losing precision through the
+    /// round trip via `Instant::now()`.
+    fn create_at(deadline: Instant) -> Self
+    where
+        Self: Sized,
+    {
+        Self::create(deadline.saturating_duration_since(Instant::now()))
+    }
 }
 
 /// The timeout generated by [tokio_timeout()].
@@ -70,7 +86,7 @@ impl ToTimeout for TokioTimeout {
 }
 
 /// The error type returned when the timeout is reached.
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 pub struct Elapsed;
 
 impl std::fmt::Display for Elapsed {
@@ -102,6 +118,7 @@ pin_project! {
         #[pin]
         delay: Option<BoxFuture<'static, ()>>,
         callback: F, // callback to create the timer
+        check_deadline_first: bool,
     }
 }
 
@@ -114,8 +131,71 @@ where
             value,
             delay: None,
             callback: F::create(d),
+            check_deadline_first: false,
+        }
+    }
+
+    pub(crate) fn new_with_deadline(value: T, deadline: Instant) -> Timeout<T, F> {
+        Timeout {
+            value,
+            delay: None,
+            callback: F::create_at(deadline),
+            check_deadline_first: false,
         }
     }
+
+    /// Replace this timeout's deadline with one `new_duration` from now.
+    ///
+    /// The cached timer, if any, is dropped so the next poll lazily re-arms against the new
+    /// timer (for the fast timeouts, a tick-rounded, shared one). This is much cheaper than constructing a brand new
+    /// [Timeout] every time a deadline needs to be pushed out, e.g. resetting an idle timeout
+    /// each time bytes flow.
+    pub fn reset(self: Pin<&mut Self>, new_duration: Duration) {
+        let mut me = self.project();
+        *me.callback = F::create(new_duration);
+        me.delay.set(None);
+    }
+
+    /// Same as [`Timeout::reset`] but takes an absolute deadline instead of a duration.
+    pub fn reset_at(self: Pin<&mut Self>, deadline: Instant) {
+        let mut me = self.project();
+        *me.callback = F::create_at(deadline);
+        me.delay.set(None);
+    }
+}
+
+impl<T, F> Timeout<T, F> {
+    /// Consume the timeout, returning the wrapped future.
+    ///
+    /// This drops the timer, so the returned future can be re-armed elsewhere (e.g. re-wrapped
+    /// in a fresh [Timeout] for a retry) instead of being lost along with the timeout.
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+
+    /// Get a reference to the wrapped future.
+    pub fn get_ref(&self) -> &T {
+        &self.value
+    }
+
+    /// Get a mutable reference to the wrapped future.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+
+    /// Check the deadline before polling the wrapped future, instead of after.
+    ///
+    /// By default, and on the fast path, a [Timeout] polls the wrapped future first and only
+    /// consults the deadline if the future is still pending, avoiding the cost of arming a
+    /// timer for futures that resolve immediately. As the [tokio::time::timeout] docs note, the
+    /// downside is that a future which never yields can run past its deadline without ever
+    /// producing [Elapsed]. This opts into the other semantics instead: once the timer has been
+    /// armed by an earlier poll, it's checked *before* giving the wrapped future another chance
+    /// to run, so a tight, non-yielding future is still bounded by the deadline.
+    pub fn check_deadline_first(mut self) -> Self {
+        self.check_deadline_first = true;
+        self
+    }
 }
 
 impl<T, F> Future for Timeout<T, F>
@@ -128,7 +208,19 @@ where
     fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
         let mut me = self.project();
 
-        // First, try polling the future
+        // If the timer has already been armed by an earlier poll and this timeout checks the
+        // deadline first, consult it before giving the future another chance to run. This
+        // bounds futures that never yield, at the cost of an extra branch on the common,
+        // already-pending path.
+        if *me.check_deadline_first {
+            if let Some(delay) = (*me.delay).as_mut() {
+                if let Poll::Ready(()) = delay.as_mut().poll(cx) {
+                    return Poll::Ready(Err(Elapsed {}));
+                }
+            }
+        }
+
+        // Try polling the future
         if let Poll::Ready(v) = me.value.poll(cx) {
             return Poll::Ready(Ok(v));
         }
@@ -156,6 +248,51 @@ mod tests {
         assert!(to.await.is_err())
     }
 
+    #[tokio::test]
+    async fn test_into_inner() {
+        // into_inner() should hand back a future that still runs to completion on its own
+        let fut = async { 1 };
+        let to = timeout(Duration::from_secs(1), fut);
+        let fut = to.into_inner();
+        assert_eq!(fut.await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_get_ref_and_mut() {
+        let fut = async { 1 };
+        let mut to = timeout(Duration::from_secs(1), fut);
+        let _ = to.get_ref();
+        let _ = to.get_mut();
+        assert_eq!(to.await.unwrap(), 1)
+    }
+
+    #[tokio::test]
+    async fn test_reset() {
+        let fut = async {
+            tokio_sleep(Duration::from_secs(1000)).await;
+            1
+        };
+        let to = timeout(Duration::from_millis(20), fut);
+        tokio::pin!(to);
+
+        // arm the original, short-lived timer
+        assert!(futures::poll!(to.as_mut()).is_pending());
+
+        // push the deadline out before the original one would have fired
+        to.as_mut().reset(Duration::from_secs(10));
+
+        // the original 20ms deadline has long since passed, but the reset timeout doesn't fire
+        tokio_sleep(Duration::from_millis(100)).await;
+        assert!(futures::poll!(to.as_mut()).is_pending());
+    }
+
+    #[tokio::test]
+    async fn test_timeout_at() {
+        let fut = tokio_sleep(Duration::from_secs(1000));
+        let to = timeout_at(Instant::now() + Duration::from_secs(1), fut);
+        assert!(to.await.is_err())
+    }
+
     #[tokio::test]
     async fn test_instantly_return() {
         let fut = async { 1 };
@@ -172,4 +309,35 @@ mod tests {
         let to = timeout(Duration::from_secs(1000), fut);
         assert_eq!(to.await.unwrap(), 1)
     }
+
+    #[tokio::test]
+    async fn test_check_deadline_first() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        // a future that never completes, tracking how many times it's polled
+        struct CountPending(Arc<AtomicUsize>);
+        impl Future for CountPending {
+            type Output = ();
+            fn poll(self: Pin<&mut Self>, _cx: &mut task::Context<'_>) -> Poll<()> {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                Poll::Pending
+            }
+        }
+
+        let polls = Arc::new(AtomicUsize::new(0));
+        let to = timeout(Duration::from_millis(1), CountPending(polls.clone()))
+            .check_deadline_first();
+        tokio::pin!(to);
+
+        // the first poll arms the timer and gives the future its usual first chance to run
+        assert!(futures::poll!(to.as_mut()).is_pending());
+        assert_eq!(polls.load(Ordering::SeqCst), 1);
+
+        // once the deadline has passed, the next poll should see that *before* polling the
+        // never-ending future again
+        tokio_sleep(Duration::from_millis(20)).await;
+        assert!(matches!(futures::poll!(to.as_mut()), Poll::Ready(Err(_))));
+        assert_eq!(polls.load(Ordering::SeqCst), 1);
+    }
 }
@@ -0,0 +1,213 @@
+// Copyright 2024 Cloudflare, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The shared timer behind [`crate::fast_timeout`].
+//!
+//! Every deadline is rounded up to the next tick so that many callers asking for almost the
+//! same deadline share a single underlying `tokio::time::sleep()` rather than each arming its
+//! own timer.
+
+use futures::future::{BoxFuture, FutureExt, WeakShared};
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use tokio::time::{sleep_until, Instant as TokioInstant};
+
+pub use std::time::Instant;
+
+/// The tick resolution used by the process-wide default [`TimerManager`] unless overridden via
+/// [`TimerManagerBuilder`].
+pub const DEFAULT_RESOLUTION: Duration = Duration::from_millis(10);
+
+/// A factory of coalesced, tick-rounded timers.
+///
+/// All deadlines handed to a given `TimerManager` are rounded up to the next multiple of its
+/// `resolution`. Two calls that round to the same deadline share one underlying sleep task
+/// instead of each spawning their own, which is what makes this crate cheap to use on busy,
+/// highly concurrent IO. Build one with [`TimerManagerBuilder`] to pick a resolution other than
+/// [`DEFAULT_RESOLUTION`].
+pub struct TimerManager {
+    resolution: Duration,
+    zero: Instant,
+    timers: Mutex<HashMap<Instant, WeakShared<BoxFuture<'static, ()>>>>,
+}
+
+impl TimerManager {
+    /// Create a new manager whose timers are rounded up to the next multiple of `resolution`.
+    pub fn new(resolution: Duration) -> Self {
+        TimerManager {
+            resolution,
+            zero: Instant::now(),
+            timers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The tick resolution this manager rounds deadlines up to.
+    pub fn resolution(&self) -> Duration {
+        self.resolution
+    }
+
+    // Round `deadline` up to the next tick of `self.resolution`, never earlier than `deadline`.
+    fn round(&self, deadline: Instant) -> Instant {
+        let tick = self.resolution.as_nanos().max(1);
+        let elapsed = deadline.saturating_duration_since(self.zero).as_nanos();
+        let rounded = elapsed.div_ceil(tick) * tick;
+        self.zero + Duration::from_nanos(rounded as u64)
+    }
+
+    /// Return a future that resolves once `deadline`, rounded up to this manager's resolution,
+    /// has passed. The underlying timer is shared with any other caller rounding to the same
+    /// deadline.
+    pub fn timeout_at(&self, deadline: Instant) -> BoxFuture<'static, ()> {
+        let rounded = self.round(deadline);
+
+        let mut timers = self.timers.lock().unwrap();
+        if let Some(shared) = timers.get(&rounded).and_then(WeakShared::upgrade) {
+            return Box::pin(shared);
+        }
+
+        let sleep: BoxFuture<'static, ()> = Box::pin(sleep_until(TokioInstant::from_std(rounded)));
+        let shared = sleep.shared();
+        // `downgrade` only fails if the Shared future has already completed, which can't happen
+        // here since it was just created.
+        if let Some(weak) = shared.downgrade() {
+            timers.insert(rounded, weak);
+        }
+        // Opportunistically forget timers nobody is waiting on anymore instead of paying for a
+        // background sweep.
+        timers.retain(|_, timer| timer.upgrade().is_some());
+
+        Box::pin(shared)
+    }
+
+    /// Return a future that resolves once `duration` from now has elapsed, rounded up to this
+    /// manager's resolution. See [`TimerManager::timeout_at`].
+    pub fn timeout(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        self.timeout_at(Instant::now() + duration)
+    }
+}
+
+impl Default for TimerManager {
+    fn default() -> Self {
+        TimerManager::new(DEFAULT_RESOLUTION)
+    }
+}
+
+/// A builder for [`TimerManager`].
+///
+/// ```
+/// use pingora_timeout::timer::TimerManagerBuilder;
+/// use std::time::Duration;
+///
+/// let low_latency_timers = TimerManagerBuilder::new()
+///     .resolution(Duration::from_millis(1))
+///     .build();
+/// ```
+#[derive(Default)]
+pub struct TimerManagerBuilder {
+    resolution: Option<Duration>,
+}
+
+impl TimerManagerBuilder {
+    /// Create a new builder, defaulting to [`DEFAULT_RESOLUTION`].
+    pub fn new() -> Self {
+        TimerManagerBuilder::default()
+    }
+
+    /// Set the tick resolution that deadlines are rounded up to.
+    pub fn resolution(mut self, resolution: Duration) -> Self {
+        self.resolution = Some(resolution);
+        self
+    }
+
+    /// Build the configured [`TimerManager`].
+    pub fn build(self) -> TimerManager {
+        TimerManager::new(self.resolution.unwrap_or(DEFAULT_RESOLUTION))
+    }
+
+    /// Build the configured [`TimerManager`] and install it as the process-wide default used by
+    /// [`crate::fast_timeout::fast_timeout`] and [`crate::fast_timeout::fast_sleep`].
+    ///
+    /// This can only succeed once per process: if a default has already been installed, either
+    /// explicitly or implicitly by an earlier call to [`timer_manager()`], the built manager is
+    /// handed back as `Err` instead.
+    pub fn set_as_default(self) -> Result<(), TimerManager> {
+        DEFAULT_MANAGER.set(self.build())
+    }
+}
+
+static DEFAULT_MANAGER: OnceLock<TimerManager> = OnceLock::new();
+
+/// The process-wide [`TimerManager`] used by [`crate::fast_timeout::fast_timeout`] and
+/// [`crate::fast_timeout::fast_sleep`].
+///
+/// Defaults to a manager with [`DEFAULT_RESOLUTION`] (10ms) the first time it is used, unless
+/// [`TimerManagerBuilder::set_as_default`] installed a different one first.
+pub fn timer_manager() -> &'static TimerManager {
+    DEFAULT_MANAGER.get_or_init(TimerManager::default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_never_fires_early() {
+        let mgr = TimerManager::new(Duration::from_millis(10));
+        let now = Instant::now();
+        for millis in 0..25 {
+            let deadline = now + Duration::from_millis(millis);
+            assert!(mgr.round(deadline) >= deadline);
+        }
+    }
+
+    #[test]
+    fn test_different_resolutions_round_independently() {
+        let fine = TimerManager::new(Duration::from_millis(1));
+        let coarse = TimerManager::new(Duration::from_millis(100));
+
+        let deadline = Instant::now() + Duration::from_millis(5);
+        assert_ne!(fine.round(deadline), coarse.round(deadline));
+        assert!(fine.round(deadline) >= deadline);
+        assert!(coarse.round(deadline) >= deadline);
+    }
+
+    #[tokio::test]
+    async fn test_managers_coalesce_independently() {
+        let fine = TimerManager::new(Duration::from_millis(1));
+        let coarse = TimerManager::new(Duration::from_millis(100));
+        let deadline = Instant::now() + Duration::from_millis(5);
+
+        // two requests on the same manager for the same deadline share one timer entry
+        let a = fine.timeout_at(deadline);
+        let b = fine.timeout_at(deadline);
+        assert_eq!(fine.timers.lock().unwrap().len(), 1);
+
+        // the other manager's coalesced set is entirely separate
+        let c = coarse.timeout_at(deadline);
+        assert_eq!(coarse.timers.lock().unwrap().len(), 1);
+
+        a.await;
+        b.await;
+        c.await;
+    }
+
+    #[test]
+    fn test_builder_sets_resolution() {
+        let mgr = TimerManagerBuilder::new()
+            .resolution(Duration::from_millis(5))
+            .build();
+        assert_eq!(mgr.resolution(), Duration::from_millis(5));
+    }
+}